@@ -0,0 +1,153 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! One-time initialization built on the [`six`](crate::six) lock's intent state.
+
+use crate::bindings;
+use crate::six::SixLock;
+use alloc::boxed::Box;
+use core::{
+    cell::UnsafeCell,
+    ops::Deref,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// A cell which can be written to only once, using a `six_lock`'s intent state so that
+/// concurrent readers are never blocked out by an in-progress initializer.
+pub struct SixOnce<T> {
+    /// Boxed so its address stays stable if `self` moves; `lock` holds a pointer to it.
+    raw: Box<UnsafeCell<bindings::six_lock>>,
+    lock: SixLock<Option<T>>,
+    initialized: AtomicBool,
+}
+
+// SAFETY: `raw`'s `UnsafeCell` is never touched directly, only through `six_lock_init` at
+// construction and through the FFI calls `lock` makes on the same pointer; `lock` is itself
+// manually `Send`/`Sync` under the same reasoning as `SixLock<T>`.
+unsafe impl<T: Send> Send for SixOnce<T> {}
+// SAFETY: as above.
+unsafe impl<T: Send + Sync> Sync for SixOnce<T> {}
+
+impl<T> SixOnce<T> {
+    /// Create a new, uninitialized cell.
+    pub fn new() -> Self {
+        let raw = Box::new(UnsafeCell::new(
+            // SAFETY: `six_lock_init` below fully initializes the zeroed value.
+            unsafe { core::mem::zeroed() },
+        ));
+        // SAFETY: `raw` was just allocated and is not yet visible to any other thread.
+        unsafe {
+            bindings::six_lock_init(raw.get());
+        }
+        // SAFETY: `raw.get()` stays valid for as long as `raw` is alive, which is at least as
+        // long as the `SixLock` stored alongside it.
+        //
+        // Stats are disabled: `get`'s lock-free fast path means wait/hold times here would only
+        // ever reflect the rare initializing call anyway, and `call_once`'s own read lock is
+        // exactly the read-heavy case `SixLockStats`'s shared busy-spin shouldn't serialize.
+        let lock = unsafe { SixLock::new_without_stats(raw.get(), None) };
+        Self {
+            raw,
+            lock,
+            initialized: AtomicBool::new(false),
+        }
+    }
+
+    /// Get a reference to the value, if it has been initialized.
+    pub fn get(&self) -> Option<&T> {
+        if self.initialized.load(Ordering::Acquire) {
+            // SAFETY: `initialized` is only set, with Release ordering, after the value has been
+            // written under the lock and is never written again, so the Acquire load above makes
+            // further shared access sound without taking the lock itself.
+            Some(unsafe { &*self.lock.data_ptr() }.as_ref().expect(
+                "value initialized by call_once below",
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Initialize the cell by calling `f`, unless it has already been initialized.
+    ///
+    /// Returns at once, without locking, if the cell is already initialized. Otherwise takes a
+    /// read lock to re-check, then `try_upgrade`s to intent to become the sole
+    /// initializer, falling back to taking intent directly if another reader won the upgrade
+    /// race. The value is written under a promoted write lock, after which the intent lock is
+    /// `downgrade`d, so concurrent readers are never blocked out by an in-progress initializer
+    /// and, being guards, are released correctly even if `f` unwinds.
+    pub fn call_once(&self, f: impl FnOnce() -> T) {
+        if self.initialized.load(Ordering::Acquire) {
+            return;
+        }
+        let read = self.lock.read();
+        if read.is_some() {
+            self.initialized.store(true, Ordering::Release);
+            return;
+        }
+        let mut intent = match read.try_upgrade() {
+            Ok(intent) => intent,
+            Err(read) => {
+                // Release our read lock first: the winner's write lock waits for readers to drain.
+                drop(read);
+                self.lock.intent()
+            }
+        };
+        if intent.is_none() {
+            intent.write().replace(f());
+            self.initialized.store(true, Ordering::Release);
+        }
+        intent.downgrade();
+    }
+
+    /// Get a reference to the value, initializing it by calling `f` if necessary.
+    pub fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+        self.call_once(f);
+        self.get().expect("value initialized by call_once above")
+    }
+}
+
+impl<T> Default for SixOnce<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A value that is lazily initialized on first access.
+///
+/// `Lazy::new` is not a `const fn` (it allocates and initializes the underlying `six_lock`), so
+/// a `Lazy` cannot be used as the initializer of a `static`.
+pub struct Lazy<T, F = fn() -> T> {
+    cell: SixOnce<T>,
+    init: UnsafeCell<Option<F>>,
+}
+
+// SAFETY: `init` is only ever read by the single thread that wins `SixOnce::call_once`'s intent
+// lock, same reasoning as `std::sync::LazyLock`.
+unsafe impl<T: Send + Sync, F: Send> Sync for Lazy<T, F> {}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    /// Create a new lazy value, to be initialized by `f` on first access.
+    pub fn new(f: F) -> Self {
+        Self {
+            cell: SixOnce::new(),
+            init: UnsafeCell::new(Some(f)),
+        }
+    }
+
+    /// Force initialization of `this`, returning a reference to its value.
+    pub fn force(this: &Self) -> &T {
+        this.cell.get_or_init(|| {
+            // SAFETY: `call_once` guarantees this closure runs at most once, so there is no
+            // other access to `init` to race with this one.
+            let f = unsafe { (*this.init.get()).take() }
+                .expect("Lazy initializer should only run once");
+            f()
+        })
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        Self::force(self)
+    }
+}
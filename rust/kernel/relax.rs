@@ -0,0 +1,75 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Strategies for how a spinning lock acquisition behaves while it waits.
+//!
+//! Based on the `relax` module of the `spin` crate.
+
+use crate::bindings;
+
+/// Customizes the behaviour of spinning lock acquisitions.
+///
+/// A `Relax` is polled once per failed acquisition attempt. It returns `true` if the caller
+/// should keep spinning, or `false` once it should give up and fall back to a sleeping
+/// acquisition path.
+pub trait Relax: Default {
+    /// Called after a failed acquisition attempt.
+    fn relax(&mut self) -> bool;
+}
+
+/// Spins calling [`core::hint::spin_loop`], forever.
+#[derive(Default)]
+pub struct SpinHint;
+
+impl Relax for SpinHint {
+    fn relax(&mut self) -> bool {
+        core::hint::spin_loop();
+        true
+    }
+}
+
+/// Yields the CPU to the scheduler on every failed attempt, forever.
+#[derive(Default)]
+pub struct YieldNow;
+
+impl Relax for YieldNow {
+    fn relax(&mut self) -> bool {
+        // SAFETY: `cond_resched` may be called from any context that is allowed to sleep.
+        unsafe {
+            bindings::cond_resched();
+        }
+        true
+    }
+}
+
+/// Spins with a doubling spin count, up to a cap, then gives up.
+pub struct ExponentialBackoff {
+    spins: u32,
+    cap: u32,
+}
+
+impl ExponentialBackoff {
+    /// Create a new backoff, doubling the spin count on each failed attempt up to `cap` before
+    /// giving up.
+    pub const fn new(cap: u32) -> Self {
+        Self { spins: 1, cap }
+    }
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> Self {
+        Self::new(1024)
+    }
+}
+
+impl Relax for ExponentialBackoff {
+    fn relax(&mut self) -> bool {
+        if self.spins >= self.cap {
+            return false;
+        }
+        for _ in 0..self.spins {
+            core::hint::spin_loop();
+        }
+        self.spins = self.spins.saturating_mul(2).min(self.cap);
+        true
+    }
+}
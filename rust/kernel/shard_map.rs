@@ -0,0 +1,246 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! A `dashmap`-style sharded concurrent map built on [`SixLock`](crate::six::SixLock).
+//!
+//! Entries are partitioned across shards, each independently guarded by a `SixLock`. Plain
+//! lookups take a per-shard read lock; a check-then-modify insert takes an *intent* lock first
+//! (which still allows concurrent readers of that shard) and only promotes to a write lock once
+//! it actually needs to mutate, avoiding the race a plain `RwLock`-based map has between dropping
+//! a read lock and taking a write lock.
+//!
+//! `alloc` has no hash map, so each shard is a `BTreeMap`; `K` is hashed only to pick a shard.
+
+use crate::bindings;
+use crate::six::{IntentGuard, MappedReadGuard, MappedWriteGuard, ReadGuard, SixLock};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::hash::{Hash, Hasher};
+use core::ops::{Deref, DerefMut};
+
+/// A shard's `six_lock` is boxed so its address stays stable when the owning `Shard` is moved
+/// (e.g. while `Vec::push`ing shards during construction).
+struct Shard<K, V> {
+    raw: Box<UnsafeCell<bindings::six_lock>>,
+    lock: SixLock<BTreeMap<K, V>>,
+}
+
+// SAFETY: `raw`'s `UnsafeCell` is never touched directly, only through `six_lock_init` at
+// construction and through the FFI calls `lock` makes on the same pointer; `lock` is itself
+// manually `Send`/`Sync` under the same reasoning as `SixLock<T>`.
+unsafe impl<K: Send, V: Send> Send for Shard<K, V> {}
+// SAFETY: as above.
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for Shard<K, V> {}
+
+impl<K: Ord, V> Shard<K, V> {
+    fn new() -> Self {
+        let raw = Box::new(UnsafeCell::new(
+            // SAFETY: `six_lock_init` below fully initializes the zeroed value.
+            unsafe { core::mem::zeroed() },
+        ));
+        // SAFETY: `raw` was just allocated and is not yet visible to any other thread.
+        unsafe {
+            bindings::six_lock_init(raw.get());
+        }
+        // SAFETY: `raw.get()` stays valid for as long as `raw` is alive, which is at least as
+        // long as the `SixLock` stored alongside it.
+        //
+        // Stats are disabled: a shard is exactly the many-concurrent-readers case that makes
+        // `SixLockStats`'s shared busy-spin serialize contention it shouldn't.
+        let lock = unsafe { SixLock::new_without_stats(raw.get(), BTreeMap::new()) };
+        Self { raw, lock }
+    }
+}
+
+/// A simple, non-cryptographic FNV-1a hasher, used only to pick a shard.
+struct ShardHasher(u64);
+
+impl Default for ShardHasher {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for ShardHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= u64::from(b);
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+fn default_shard_count() -> usize {
+    // SAFETY: FFI call.
+    let cpus = unsafe { bindings::num_online_cpus() } as usize;
+    cpus.max(1).next_power_of_two() * 4
+}
+
+/// A concurrent map, partitioned into shards each guarded by an independent [`SixLock`].
+pub struct ShardedMap<K, V> {
+    shards: Vec<Shard<K, V>>,
+}
+
+// SAFETY: restates `Shard<K, V>`'s own bounds; spelled out explicitly since this is the type
+// callers actually share across threads (e.g. behind an `Arc` or in a `static`).
+unsafe impl<K: Send, V: Send> Send for ShardedMap<K, V> {}
+// SAFETY: as above.
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for ShardedMap<K, V> {}
+
+impl<K: Ord + Hash, V> ShardedMap<K, V> {
+    /// Create a new map, sizing the shard count from the number of online CPUs.
+    pub fn new() -> Self {
+        Self::with_shards(default_shard_count())
+    }
+
+    /// Create a new map with exactly `shard_count` shards (rounded up to at least one).
+    pub fn with_shards(shard_count: usize) -> Self {
+        let shard_count = shard_count.max(1);
+        let mut shards = Vec::with_capacity(shard_count);
+        for _ in 0..shard_count {
+            shards.push(Shard::new());
+        }
+        Self { shards }
+    }
+
+    fn shard_for(&self, key: &K) -> &Shard<K, V> {
+        let mut hasher = ShardHasher::default();
+        key.hash(&mut hasher);
+        &self.shards[(hasher.finish() as usize) % self.shards.len()]
+    }
+
+    /// Look up `key`, returning a read-locked, projected reference to its value if present.
+    pub fn get(&self, key: &K) -> Option<MappedReadGuard<'_, V>> {
+        let guard = self.shard_for(key).lock.read();
+        if guard.contains_key(key) {
+            Some(guard.map(|shard| shard.get(key).unwrap()))
+        } else {
+            None
+        }
+    }
+
+    /// Insert `value` for `key`, returning the previous value if any.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let mut intent = self.shard_for(&key).lock.intent();
+        let old = intent.write().insert(key, value);
+        old
+    }
+
+    /// Remove `key`, returning its value if present.
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let mut intent = self.shard_for(key).lock.intent();
+        let old = intent.write().remove(key);
+        old
+    }
+
+    /// Get the value for `key`, inserting the result of `default` first if it is absent.
+    ///
+    /// Takes a single intent lock for the whole operation: existing entries are read back out
+    /// without ever taking a write lock, and only a genuine insert promotes to one, instead of
+    /// racing a separate read lock and write lock against other threads.
+    pub fn get_or_insert_with(&self, key: K, default: impl FnOnce() -> V) -> MappedReadGuard<'_, V>
+    where
+        K: Clone,
+    {
+        let mut intent = self.shard_for(&key).lock.intent();
+        if !intent.contains_key(&key) {
+            intent.write().insert(key.clone(), default());
+        }
+        intent
+            .downgrade()
+            .map(|shard| shard.get(&key).unwrap())
+    }
+
+    /// Call `f` with a read lock on each shard in turn.
+    pub fn for_each_shard(&self, mut f: impl FnMut(ReadGuard<'_, BTreeMap<K, V>>)) {
+        for shard in &self.shards {
+            f(shard.lock.read());
+        }
+    }
+
+    /// Get a view of `key`'s entry, taking the shard's intent lock for the duration of `Entry`,
+    /// so a check (is the key present?) and a later modification share a single lock acquisition
+    /// rather than racing a separate read lock and write lock against other threads.
+    pub fn entry(&self, key: K) -> Entry<'_, K, V> {
+        Entry {
+            intent: self.shard_for(&key).lock.intent(),
+            key,
+        }
+    }
+}
+
+impl<K: Ord + Hash, V> Default for ShardedMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A view into a single entry of a [`ShardedMap`], obtained by [`ShardedMap::entry`].
+///
+/// Holds the shard's intent lock, which still allows concurrent readers of the shard; promoting
+/// to a write lock is deferred to whichever method actually needs to mutate.
+pub struct Entry<'a, K, V> {
+    intent: IntentGuard<'a, BTreeMap<K, V>>,
+    key: K,
+}
+
+impl<'a, K: Ord, V> Entry<'a, K, V> {
+    /// Ensure a value is present, inserting `default` if absent, and return a write-locked,
+    /// projected reference to it.
+    pub fn or_insert(self, default: V) -> EntryGuard<'a, K, V> {
+        self.or_insert_with(|| default)
+    }
+
+    /// Ensure a value is present, inserting the result of calling `default` if absent, and
+    /// return a write-locked, projected reference to it.
+    pub fn or_insert_with(mut self, default: impl FnOnce() -> V) -> EntryGuard<'a, K, V> {
+        let key = self.key;
+        let mapped = self
+            .intent
+            .write()
+            .map(|shard| shard.entry(key).or_insert_with(default));
+        EntryGuard {
+            mapped,
+            intent: self.intent,
+        }
+    }
+
+    /// Apply `f` to the value if present, returning `self` unchanged either way.
+    pub fn and_modify(mut self, f: impl FnOnce(&mut V)) -> Self {
+        if self.intent.contains_key(&self.key) {
+            if let Some(value) = self.intent.write().get_mut(&self.key) {
+                f(value);
+            }
+        }
+        self
+    }
+}
+
+/// A write-locked, projected reference to an [`Entry`]'s value, returned by
+/// [`Entry::or_insert`]/[`Entry::or_insert_with`].
+///
+/// The write lock is a promotion of the `Entry`'s intent lock, so this keeps the `IntentGuard`
+/// alive underneath the `MappedWriteGuard` and relies on in-declaration-order field drop to
+/// release the write lock before the intent lock, matching the order they were acquired in.
+pub struct EntryGuard<'a, K, V> {
+    mapped: MappedWriteGuard<'a, V>,
+    intent: IntentGuard<'a, BTreeMap<K, V>>,
+}
+
+impl<K, V> Deref for EntryGuard<'_, K, V> {
+    type Target = V;
+    fn deref(&self) -> &V {
+        &self.mapped
+    }
+}
+
+impl<K, V> DerefMut for EntryGuard<'_, K, V> {
+    fn deref_mut(&mut self) -> &mut V {
+        &mut self.mapped
+    }
+}
@@ -8,83 +8,294 @@
 #![allow(missing_docs)]
 
 use crate::bindings;
-use core::{ffi::c_void, ptr::null_mut};
+use crate::mean_and_variance::{MeanAndVarianceStats, MeanAndVarianceWeighted};
+use crate::relax::Relax;
+use core::{
+    cell::UnsafeCell,
+    ffi::c_void,
+    marker::PhantomData,
+    mem::forget,
+    ops::{Deref, DerefMut},
+    ptr::null_mut,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Exponential weight applied to lock contention samples; see [`MeanAndVarianceWeighted`].
+const STATS_WEIGHT: u8 = 8;
+
+fn now_ns() -> i64 {
+    // SAFETY: FFI call.
+    unsafe { bindings::ktime_get_ns() as i64 }
+}
+
+/// Exponentially-weighted wait/hold time statistics for a [`SixLock`].
+///
+/// `MeanAndVarianceWeighted` has no internal synchronization, so updates are serialized with a
+/// short spin. That spin is paid on every acquire and every guard `Drop`, which is fine for a
+/// lock that isn't under heavy concurrent-reader load but defeats the point of one that is; call
+/// sites that can't afford it should construct their `SixLock` with stats disabled instead.
+struct SixLockStats {
+    enabled: bool,
+    busy: AtomicBool,
+    wait: UnsafeCell<MeanAndVarianceWeighted>,
+    hold: UnsafeCell<MeanAndVarianceWeighted>,
+}
+
+// SAFETY: `busy` serializes all access to `wait`/`hold`.
+unsafe impl Sync for SixLockStats {}
+
+impl SixLockStats {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            busy: AtomicBool::new(false),
+            wait: UnsafeCell::new(MeanAndVarianceWeighted::new(STATS_WEIGHT)),
+            hold: UnsafeCell::new(MeanAndVarianceWeighted::new(STATS_WEIGHT)),
+        }
+    }
+
+    fn with_locked<R>(
+        &self,
+        f: impl FnOnce(&mut MeanAndVarianceWeighted, &mut MeanAndVarianceWeighted) -> R,
+    ) -> R {
+        while self
+            .busy
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        // SAFETY: `busy` was just acquired above, giving exclusive access to `wait`/`hold`.
+        let result = f(unsafe { &mut *self.wait.get() }, unsafe {
+            &mut *self.hold.get()
+        });
+        self.busy.store(false, Ordering::Release);
+        result
+    }
+
+    fn record_wait(&self, ns: i64) {
+        if !self.enabled {
+            return;
+        }
+        self.with_locked(|wait, _hold| wait.update(ns));
+    }
+
+    fn record_hold(&self, ns: i64) {
+        if !self.enabled {
+            return;
+        }
+        self.with_locked(|_wait, hold| hold.update(ns));
+    }
+
+    fn snapshot_wait(&self) -> MeanAndVarianceWeighted {
+        if !self.enabled {
+            return MeanAndVarianceWeighted::new(STATS_WEIGHT);
+        }
+        self.with_locked(|wait, _hold| *wait)
+    }
+
+    fn snapshot_hold(&self) -> MeanAndVarianceWeighted {
+        if !self.enabled {
+            return MeanAndVarianceWeighted::new(STATS_WEIGHT);
+        }
+        self.with_locked(|_wait, hold| *hold)
+    }
+}
+
+/// A sleepable read/write lock guarding `T`; much like a read/write semaphore, but with a third
+/// intermediate state, intent.
+pub struct SixLock<T> {
+    lock: *mut bindings::six_lock,
+    data: UnsafeCell<T>,
+    stats: SixLockStats,
+}
+
+// SAFETY: `SixLock<T>` lets its guards hand out `&T`/`&mut T` from any thread that can acquire
+// the underlying `six_lock`, exactly as `std::sync::RwLock<T>` does.
+unsafe impl<T: Send> Send for SixLock<T> {}
+// SAFETY: as above; a shared `&SixLock<T>` can produce a `&T` on any thread holding a read or
+// intent lock, so `T` must also be `Sync`.
+unsafe impl<T: Send + Sync> Sync for SixLock<T> {}
+
+impl<T> SixLock<T> {
+    /// Wrap an already-initialized `six_lock` together with the data it protects.
+    ///
+    /// Wait/hold time statistics are tracked by default; use [`Self::new_without_stats`] for
+    /// locks where many concurrent readers make that overhead unacceptable.
+    ///
+    /// # Safety
+    ///
+    /// `lock` must point to a `six_lock` that has been initialized (e.g. via `six_lock_init`)
+    /// and that remains valid for as long as the returned `SixLock` exists.
+    pub unsafe fn new(lock: *mut bindings::six_lock, data: T) -> Self {
+        // SAFETY: caller's obligations are the same as this function's.
+        unsafe { Self::with_stats(lock, data, true) }
+    }
+
+    /// Like [`Self::new`], but without wait/hold time statistics tracking, avoiding the spin
+    /// `SixLockStats` would otherwise take on every acquire and every guard drop.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`Self::new`].
+    pub unsafe fn new_without_stats(lock: *mut bindings::six_lock, data: T) -> Self {
+        // SAFETY: caller's obligations are the same as this function's.
+        unsafe { Self::with_stats(lock, data, false) }
+    }
+
+    /// SAFETY: same as `new`.
+    unsafe fn with_stats(lock: *mut bindings::six_lock, data: T, stats_enabled: bool) -> Self {
+        Self {
+            lock,
+            data: UnsafeCell::new(data),
+            stats: SixLockStats::new(stats_enabled),
+        }
+    }
+
+    /// A snapshot of how long acquisitions of this lock have waited, in nanoseconds.
+    pub fn wait_stats(&self) -> MeanAndVarianceWeighted {
+        self.stats.snapshot_wait()
+    }
+
+    /// A snapshot of how long this lock has been held once acquired, in nanoseconds.
+    pub fn hold_stats(&self) -> MeanAndVarianceWeighted {
+        self.stats.snapshot_hold()
+    }
 
-/// A sleepable read/write lock; much like a read/write semaphore, but with third intermediate state, intent.
-#[repr(transparent)]
-pub struct SixLock(*mut bindings::six_lock);
+    /// Get a raw pointer to the protected data, bypassing the lock entirely.
+    ///
+    /// # Safety
+    ///
+    /// The caller must independently ensure whatever degree of exclusive or shared access it
+    /// relies on; this performs no locking and checks no lock state.
+    pub(crate) unsafe fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
 
-impl SixLock {
     /// Obtain a read lock, spinning until successful.
-    pub fn read(&self) -> ReadGuard<'_> {
+    pub fn read(&self) -> ReadGuard<'_, T> {
+        let start = now_ns();
         unsafe {
-            bindings::six_lock_read(self.0, None, null_mut());
+            bindings::six_lock_read(self.lock, None, null_mut());
         }
+        self.stats.record_wait(now_ns() - start);
         unsafe { ReadGuard::new(self) }
     }
 
     /// Obtain a read lock, sleeping if indicated by the provided closure.
-    pub fn read_or_sleep(&self, should_sleep: &mut ShouldSleepFn) -> ReadGuard<'_> {
-        let mut should_sleep_holder = ShouldSleepRefHolder(should_sleep);
+    pub fn read_or_sleep(&self, should_sleep: &mut ShouldSleepFn<'_, T>) -> ReadGuard<'_, T> {
+        let mut should_sleep_holder = ShouldSleepRefHolder {
+            lock: self,
+            f: should_sleep,
+        };
+        let start = now_ns();
         unsafe {
             bindings::six_lock_read(
-                self.0,
-                Some(rust_helper_six_locks_should_sleep),
+                self.lock,
+                Some(rust_helper_six_locks_should_sleep::<T>),
                 &mut should_sleep_holder as *mut _ as *mut c_void,
             );
         }
+        self.stats.record_wait(now_ns() - start);
         unsafe { ReadGuard::new(self) }
     }
 
     /// Attempt to obtain a read lock without blocking.
-    pub fn try_read(&self) -> Option<ReadGuard<'_>> {
-        if unsafe { bindings::six_trylock_read(self.0) } {
+    pub fn try_read(&self) -> Option<ReadGuard<'_, T>> {
+        if unsafe { bindings::six_trylock_read(self.lock) } {
             Some(unsafe { ReadGuard::new(self) })
         } else {
             None
         }
     }
 
-    /// Obtain a write lock, spinning until successful.
-    pub fn intent(&self) -> IntentGuard<'_> {
+    /// Obtain a read lock, repeatedly trying `try_read` and consulting `R` between attempts,
+    /// falling back to the sleeping acquisition path once `R` gives up.
+    pub fn read_spin<R: Relax>(&self) -> ReadGuard<'_, T> {
+        let start = now_ns();
+        let mut relax = R::default();
+        loop {
+            if let Some(guard) = self.try_read() {
+                self.stats.record_wait(now_ns() - start);
+                return guard;
+            }
+            if !relax.relax() {
+                unsafe {
+                    bindings::six_lock_read(self.lock, None, null_mut());
+                }
+                self.stats.record_wait(now_ns() - start);
+                return unsafe { ReadGuard::new(self) };
+            }
+        }
+    }
+
+    /// Obtain an intent lock, spinning until successful.
+    pub fn intent(&self) -> IntentGuard<'_, T> {
+        let start = now_ns();
         unsafe {
-            bindings::six_lock_intent(self.0, None, null_mut());
+            bindings::six_lock_intent(self.lock, None, null_mut());
         }
+        self.stats.record_wait(now_ns() - start);
         unsafe { IntentGuard::new(self) }
     }
 
     /// Obtain an intent lock, sleeping if indicated by the provided closure.
-    pub fn intent_or_sleep(&self, should_sleep: &mut ShouldSleepFn) -> IntentGuard<'_> {
-        let mut should_sleep_holder = ShouldSleepRefHolder(should_sleep);
+    pub fn intent_or_sleep(&self, should_sleep: &mut ShouldSleepFn<'_, T>) -> IntentGuard<'_, T> {
+        let mut should_sleep_holder = ShouldSleepRefHolder {
+            lock: self,
+            f: should_sleep,
+        };
+        let start = now_ns();
         unsafe {
             bindings::six_lock_intent(
-                self.0,
-                Some(rust_helper_six_locks_should_sleep),
+                self.lock,
+                Some(rust_helper_six_locks_should_sleep::<T>),
                 &mut should_sleep_holder as *mut _ as *mut c_void,
             );
         }
+        self.stats.record_wait(now_ns() - start);
         unsafe { IntentGuard::new(self) }
     }
 
     /// Attempt to obtain an intent lock without blocking.
-    pub fn try_intent(&self) -> Option<IntentGuard<'_>> {
-        if unsafe { bindings::six_trylock_intent(self.0) } {
+    pub fn try_intent(&self) -> Option<IntentGuard<'_, T>> {
+        if unsafe { bindings::six_trylock_intent(self.lock) } {
             Some(unsafe { IntentGuard::new(self) })
         } else {
             None
         }
     }
+
+    /// Obtain an intent lock, repeatedly trying `try_intent` and consulting `R` between
+    /// attempts, falling back to the sleeping acquisition path once `R` gives up.
+    pub fn intent_spin<R: Relax>(&self) -> IntentGuard<'_, T> {
+        let start = now_ns();
+        let mut relax = R::default();
+        loop {
+            if let Some(guard) = self.try_intent() {
+                self.stats.record_wait(now_ns() - start);
+                return guard;
+            }
+            if !relax.relax() {
+                unsafe {
+                    bindings::six_lock_intent(self.lock, None, null_mut());
+                }
+                self.stats.record_wait(now_ns() - start);
+                return unsafe { IntentGuard::new(self) };
+            }
+        }
+    }
 }
 
-pub struct RelockHandle<'a> {
-    lock: &'a SixLock,
+pub struct RelockHandle<'a, T> {
+    lock: &'a SixLock<T>,
     seq: u32,
 }
 
-impl<'a> RelockHandle<'a> {
+impl<'a, T> RelockHandle<'a, T> {
     /// Attempt to relock a previously-held lock for reading. Will fail if a write lock has been taken since the RelockHandle's creation.
-    pub fn try_read(&self) -> Option<ReadGuard<'a>> {
-        if unsafe { bindings::six_relock_read(self.lock.0, self.seq) } {
+    pub fn try_read(&self) -> Option<ReadGuard<'a, T>> {
+        if unsafe { bindings::six_relock_read(self.lock.lock, self.seq) } {
             Some(unsafe { ReadGuard::new(self.lock) })
         } else {
             None
@@ -92,8 +303,8 @@ impl<'a> RelockHandle<'a> {
     }
 
     /// Attempt to immediately relock a previously-held lock for intent. Will fail if a write lock has been taken since the RelockHandle's creation.
-    pub fn try_intent(&self) -> Option<IntentGuard<'a>> {
-        if unsafe { bindings::six_relock_read(self.lock.0, self.seq) } {
+    pub fn try_intent(&self) -> Option<IntentGuard<'a, T>> {
+        if unsafe { bindings::six_relock_read(self.lock.lock, self.seq) } {
             Some(unsafe { IntentGuard::new(self.lock) })
         } else {
             None
@@ -101,148 +312,361 @@ impl<'a> RelockHandle<'a> {
     }
 }
 
-pub struct ReadGuard<'a> {
-    lock: &'a SixLock,
+pub struct ReadGuard<'a, T> {
+    lock: &'a SixLock<T>,
+    hold_start: i64,
 }
 
-impl<'a> ReadGuard<'a> {
+impl<'a, T> ReadGuard<'a, T> {
     /// SAFETY: The lock must be held for reading.
-    unsafe fn new(lock: &'a SixLock) -> Self {
-        Self { lock }
+    unsafe fn new(lock: &'a SixLock<T>) -> Self {
+        Self {
+            lock,
+            hold_start: now_ns(),
+        }
     }
-    pub fn try_upgrade(self) -> Result<IntentGuard<'a>, Self> {
-        if unsafe { bindings::six_lock_tryupgrade(self.lock.0) } {
+    pub fn try_upgrade(self) -> Result<IntentGuard<'a, T>, Self> {
+        if unsafe { bindings::six_lock_tryupgrade(self.lock.lock) } {
             Ok(unsafe { IntentGuard::new(self.lock) })
         } else {
             Err(self)
         }
     }
-    pub fn relock_handle(&'a self) -> RelockHandle<'a> {
+    pub fn relock_handle(&'a self) -> RelockHandle<'a, T> {
         RelockHandle {
             lock: self.lock,
             // SAFETY: safe because we hold the lock.
-            seq: unsafe { (*self.lock.0).state.__bindgen_anon_4.seq },
+            seq: unsafe { (*self.lock.lock).state.__bindgen_anon_4.seq },
         }
     }
+
+    /// Project the guard onto a sub-field, keeping the read lock held but narrowing the
+    /// reference it derefs to.
+    pub fn map<U>(self, f: impl FnOnce(&T) -> &U) -> MappedReadGuard<'a, U> {
+        let value: *const U = f(&*self);
+        let lock = self.lock.lock;
+        let stats: *const SixLockStats = &self.lock.stats;
+        let hold_start = self.hold_start;
+        forget(self);
+        // SAFETY: `lock` is still held for reading; we only just released our `ReadGuard`
+        // without running its `Drop`.
+        unsafe { MappedReadGuard::new(lock, stats, hold_start, value) }
+    }
 }
 
-impl<'a> Clone for ReadGuard<'a> {
+impl<'a, T> Deref for ReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // SAFETY: we hold the read lock, so no writer can be mutating `data`.
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T> Clone for ReadGuard<'a, T> {
     fn clone(&self) -> Self {
         unsafe {
-            bindings::six_lock_increment(self.lock.0, bindings::six_lock_type_SIX_LOCK_read);
+            bindings::six_lock_increment(self.lock.lock, bindings::six_lock_type_SIX_LOCK_read);
         }
         unsafe { Self::new(self.lock) }
     }
 }
 
-impl Drop for ReadGuard<'_> {
+impl<T> Drop for ReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.stats.record_hold(now_ns() - self.hold_start);
+        // SAFETY: safe because we hold the lock.
+        unsafe {
+            bindings::six_unlock_read(self.lock.lock);
+        }
+    }
+}
+
+/// A read lock projected onto a sub-field by [`ReadGuard::map`].
+pub struct MappedReadGuard<'a, U> {
+    lock: *mut bindings::six_lock,
+    stats: *const SixLockStats,
+    hold_start: i64,
+    value: *const U,
+    _marker: PhantomData<&'a U>,
+}
+
+impl<'a, U> MappedReadGuard<'a, U> {
+    /// SAFETY: `lock` must be held for reading, `stats` must outlive `'a`, and `value` must
+    /// remain valid for `'a`.
+    unsafe fn new(
+        lock: *mut bindings::six_lock,
+        stats: *const SixLockStats,
+        hold_start: i64,
+        value: *const U,
+    ) -> Self {
+        Self {
+            lock,
+            stats,
+            hold_start,
+            value,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, U> Deref for MappedReadGuard<'a, U> {
+    type Target = U;
+    fn deref(&self) -> &U {
+        // SAFETY: we hold the read lock for as long as this guard exists.
+        unsafe { &*self.value }
+    }
+}
+
+impl<U> Drop for MappedReadGuard<'_, U> {
     fn drop(&mut self) {
+        // SAFETY: `stats` outlives this guard, per `new`'s invariant.
+        unsafe { &*self.stats }.record_hold(now_ns() - self.hold_start);
         // SAFETY: safe because we hold the lock.
         unsafe {
-            bindings::six_unlock_read(self.lock.0);
+            bindings::six_unlock_read(self.lock);
         }
     }
 }
 
-pub struct IntentGuard<'a> {
-    lock: &'a SixLock,
+pub struct IntentGuard<'a, T> {
+    lock: &'a SixLock<T>,
+    hold_start: i64,
 }
 
-impl<'a> IntentGuard<'a> {
+impl<'a, T> IntentGuard<'a, T> {
     /// SAFETY: The lock must be held for intent.
-    unsafe fn new(lock: &'a SixLock) -> Self {
-        Self { lock }
+    unsafe fn new(lock: &'a SixLock<T>) -> Self {
+        Self {
+            lock,
+            hold_start: now_ns(),
+        }
     }
 
     /// Obtain a write lock, spinning until successful.
-    pub fn write(&'a self) -> WriteGuard<'_> {
+    ///
+    /// Takes `&mut self` (rather than `&self`, as the other lock methods do) so that the
+    /// borrow checker rejects calling this again before the returned `WriteGuard` is dropped:
+    /// `WriteGuard` now hands out `&mut T` into the same data `self` derefs to, so two live
+    /// `WriteGuard`s from the same `IntentGuard` would alias a `&mut T`.
+    pub fn write(&mut self) -> WriteGuard<'_, 'a, T> {
+        let start = now_ns();
         unsafe {
-            bindings::six_lock_write(self.lock.0, None, null_mut());
+            bindings::six_lock_write(self.lock.lock, None, null_mut());
         }
+        self.lock.stats.record_wait(now_ns() - start);
         unsafe { WriteGuard::new(self) }
     }
 
     /// Obtain a write lock, sleeping if indicated by the provided closure.
-    pub fn write_or_sleep(&'a self, should_sleep: &mut ShouldSleepFn) -> WriteGuard<'_> {
-        let mut should_sleep_holder = ShouldSleepRefHolder(should_sleep);
+    pub fn write_or_sleep(
+        &mut self,
+        should_sleep: &mut ShouldSleepFn<'_, T>,
+    ) -> WriteGuard<'_, 'a, T> {
+        let mut should_sleep_holder = ShouldSleepRefHolder {
+            lock: self.lock,
+            f: should_sleep,
+        };
+        let start = now_ns();
         unsafe {
             bindings::six_lock_write(
-                self.lock.0,
-                Some(rust_helper_six_locks_should_sleep),
+                self.lock.lock,
+                Some(rust_helper_six_locks_should_sleep::<T>),
                 &mut should_sleep_holder as *mut _ as *mut c_void,
             );
         }
+        self.lock.stats.record_wait(now_ns() - start);
         unsafe { WriteGuard::new(self) }
     }
 
     /// Attempt to obtain a write lock without blocking.
-    pub fn try_write(&'a self) -> Option<WriteGuard<'_>> {
-        if unsafe { bindings::six_trylock_write(self.lock.0) } {
+    pub fn try_write(&mut self) -> Option<WriteGuard<'_, 'a, T>> {
+        if unsafe { bindings::six_trylock_write(self.lock.lock) } {
             Some(unsafe { WriteGuard::new(self) })
         } else {
             None
         }
     }
 
+    /// Obtain a write lock, repeatedly trying a non-blocking acquisition and consulting `R`
+    /// between attempts, falling back to the sleeping acquisition path once `R` gives up.
+    ///
+    /// Written in terms of the raw FFI calls, rather than `try_write`/`write`, so that the
+    /// borrow checker doesn't see the loop as taking more than one `WriteGuard` from `self`.
+    pub fn write_spin<R: Relax>(&mut self) -> WriteGuard<'_, 'a, T> {
+        let start = now_ns();
+        let mut relax = R::default();
+        loop {
+            if unsafe { bindings::six_trylock_write(self.lock.lock) } {
+                break;
+            }
+            if !relax.relax() {
+                unsafe {
+                    bindings::six_lock_write(self.lock.lock, None, null_mut());
+                }
+                break;
+            }
+        }
+        self.lock.stats.record_wait(now_ns() - start);
+        unsafe { WriteGuard::new(self) }
+    }
+
     /// Convert an intent lock into a read lock.
-    pub fn downgrade(self) -> ReadGuard<'a> {
+    pub fn downgrade(self) -> ReadGuard<'a, T> {
         unsafe {
-            bindings::six_lock_downgrade(self.lock.0);
+            bindings::six_lock_downgrade(self.lock.lock);
         }
         unsafe { ReadGuard::new(self.lock) }
     }
 }
 
-impl Clone for IntentGuard<'_> {
-    fn clone(&self) -> Self {
+impl<'a, T> Deref for IntentGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // SAFETY: the intent state excludes other writers, so `data` may be read.
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+// No `Clone` impl: unlike `ReadGuard`, an `IntentGuard` can promote to a `WriteGuard` that hands
+// out `&mut T`, so two independently-owned `IntentGuard`s over the same `SixLock` would let safe
+// code call `.write()` on each and hold two live `&mut T` into the same data at once.
+
+impl<T> Drop for IntentGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.stats.record_hold(now_ns() - self.hold_start);
+        // SAFETY: safe because we hold the lock.
         unsafe {
-            bindings::six_lock_increment(self.lock.0, bindings::six_lock_type_SIX_LOCK_intent);
+            bindings::six_unlock_intent(self.lock.lock);
         }
-        unsafe { Self::new(self.lock) }
     }
 }
 
-impl Drop for IntentGuard<'_> {
+pub struct WriteGuard<'w, 'a, T> {
+    intent: &'w mut IntentGuard<'a, T>,
+    hold_start: i64,
+}
+
+impl<'w, 'a, T> WriteGuard<'w, 'a, T> {
+    /// SAFETY: The lock must be held for writing.
+    unsafe fn new(intent: &'w mut IntentGuard<'a, T>) -> Self {
+        Self {
+            intent,
+            hold_start: now_ns(),
+        }
+    }
+
+    /// Project the guard onto a sub-field, keeping the write lock held but narrowing the
+    /// reference it derefs to.
+    pub fn map<U>(mut self, f: impl FnOnce(&mut T) -> &mut U) -> MappedWriteGuard<'a, U> {
+        let value: *mut U = f(&mut *self);
+        let lock = self.intent.lock.lock;
+        let stats: *const SixLockStats = &self.intent.lock.stats;
+        let hold_start = self.hold_start;
+        forget(self);
+        // SAFETY: `lock` is still held for writing; we only just released our `WriteGuard`
+        // without running its `Drop`.
+        unsafe { MappedWriteGuard::new(lock, stats, hold_start, value) }
+    }
+}
+
+impl<'w, 'a, T> Deref for WriteGuard<'w, 'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // SAFETY: we hold the write lock, which excludes all other access.
+        unsafe { &*self.intent.lock.data.get() }
+    }
+}
+
+impl<'w, 'a, T> DerefMut for WriteGuard<'w, 'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: we hold the write lock, which excludes all other access.
+        unsafe { &mut *self.intent.lock.data.get() }
+    }
+}
+
+impl<T> Drop for WriteGuard<'_, '_, T> {
     fn drop(&mut self) {
+        self.intent
+            .lock
+            .stats
+            .record_hold(now_ns() - self.hold_start);
         // SAFETY: safe because we hold the lock.
         unsafe {
-            bindings::six_unlock_intent(self.lock.0);
+            bindings::six_unlock_write(self.intent.lock.lock);
         }
     }
 }
 
-pub struct WriteGuard<'a> {
-    intent: &'a IntentGuard<'a>,
+/// A write lock projected onto a sub-field by [`WriteGuard::map`].
+pub struct MappedWriteGuard<'a, U> {
+    lock: *mut bindings::six_lock,
+    stats: *const SixLockStats,
+    hold_start: i64,
+    value: *mut U,
+    _marker: PhantomData<&'a mut U>,
 }
 
-impl<'a> WriteGuard<'a> {
-    /// SAFETY: The lock must be held for writing.
-    unsafe fn new(intent: &'a IntentGuard<'_>) -> Self {
-        Self { intent }
+impl<'a, U> MappedWriteGuard<'a, U> {
+    /// SAFETY: `lock` must be held for writing, `stats` must outlive `'a`, and `value` must
+    /// remain valid and exclusively borrowed for `'a`.
+    unsafe fn new(
+        lock: *mut bindings::six_lock,
+        stats: *const SixLockStats,
+        hold_start: i64,
+        value: *mut U,
+    ) -> Self {
+        Self {
+            lock,
+            stats,
+            hold_start,
+            value,
+            _marker: PhantomData,
+        }
     }
 }
 
-impl Drop for WriteGuard<'_> {
+impl<'a, U> Deref for MappedWriteGuard<'a, U> {
+    type Target = U;
+    fn deref(&self) -> &U {
+        // SAFETY: we hold the write lock for as long as this guard exists.
+        unsafe { &*self.value }
+    }
+}
+
+impl<'a, U> DerefMut for MappedWriteGuard<'a, U> {
+    fn deref_mut(&mut self) -> &mut U {
+        // SAFETY: we hold the write lock for as long as this guard exists.
+        unsafe { &mut *self.value }
+    }
+}
+
+impl<U> Drop for MappedWriteGuard<'_, U> {
     fn drop(&mut self) {
+        // SAFETY: `stats` outlives this guard, per `new`'s invariant.
+        unsafe { &*self.stats }.record_hold(now_ns() - self.hold_start);
         // SAFETY: safe because we hold the lock.
         unsafe {
-            bindings::six_unlock_write(self.intent.lock.0);
+            bindings::six_unlock_write(self.lock);
         }
     }
 }
 
-pub type ShouldSleepFn = dyn FnMut(&SixLock) -> bool;
+pub type ShouldSleepFn<'a, T> = dyn FnMut(&SixLock<T>) -> bool + 'a;
 
 /// A C-compatible container for a Rust-style fat reference to a ShouldSleepFn trait object.
-struct ShouldSleepRefHolder<'a>(&'a mut ShouldSleepFn);
+struct ShouldSleepRefHolder<'a, T> {
+    lock: &'a SixLock<T>,
+    f: &'a mut ShouldSleepFn<'a, T>,
+}
 
 /// SAFETY:
-///  - @lock must be a valid pointer to an initialized six_lock, which must live at least as long as @closure.
-///  - @closure must be a valid pointer to an initialized SixLockShouldSleepHolder.
-unsafe extern "C" fn rust_helper_six_locks_should_sleep(
-    lock: *mut bindings::six_lock,
+///  - @lock must be the `six_lock` belonging to the `SixLock` stored in @closure, which must live
+///    at least as long as @closure.
+///  - @closure must be a valid pointer to an initialized `ShouldSleepRefHolder<T>`.
+unsafe extern "C" fn rust_helper_six_locks_should_sleep<T>(
+    _lock: *mut bindings::six_lock,
     closure: *mut c_void,
 ) -> i32 {
-    let closure = unsafe { &mut *(closure as *mut ShouldSleepRefHolder<'_>) };
-    (closure.0)(&SixLock(lock)).into()
+    let closure = unsafe { &mut *(closure as *mut ShouldSleepRefHolder<'_, T>) };
+    (closure.f)(closure.lock).into()
 }